@@ -0,0 +1,82 @@
+//! An on-disk cache of downloaded asset bytes, keyed by URL
+//!
+//! Used by the fetch path in [crate::asset] to avoid re-downloading assets
+//! that haven't changed: a cache entry stores both the bytes and whatever
+//! `ETag`/`Last-Modified` validators the server sent, so the next fetch can
+//! be a conditional request and, on `304 Not Modified`, reuse the bytes
+//! already on disk instead of re-downloading them.
+
+use reqwest::Url;
+use sha2::Digest;
+use sha2::Sha256;
+
+use std::fs;
+use std::path::Path;
+use std::path::PathBuf;
+
+/// A cached entry for one URL: its bytes, plus whichever validators and
+/// `Content-Type` the server sent along with them (any of which may be
+/// absent)
+pub(crate) struct CacheEntry {
+    pub(crate) bytes: Vec<u8>,
+    pub(crate) etag: Option<String>,
+    pub(crate) last_modified: Option<String>,
+    pub(crate) content_type: Option<String>,
+}
+
+/// Load the cache entry for `url` out of `dir`, if one exists
+pub(crate) fn load(dir: &Path, url: &Url) -> Option<CacheEntry> {
+    let (bin_path, meta_path) = entry_paths(dir, url);
+
+    let bytes = fs::read(bin_path).ok()?;
+    let meta = fs::read_to_string(meta_path).ok()?;
+    let mut lines = meta.lines();
+    let etag = lines.next().filter(|line| !line.is_empty()).map(str::to_owned);
+    let last_modified = lines.next().filter(|line| !line.is_empty()).map(str::to_owned);
+    let content_type = lines.next().filter(|line| !line.is_empty()).map(str::to_owned);
+
+    Some(CacheEntry { bytes, etag, last_modified, content_type })
+}
+
+/// Write `bytes` (and whichever validators/`Content-Type` are available) to
+/// `dir` as the cache entry for `url`, overwriting any existing entry
+///
+/// Failures to write are swallowed; the cache is a best-effort optimization,
+/// not something a failed download should hinge on.
+pub(crate) fn store(
+    dir: &Path,
+    url: &Url,
+    bytes: &[u8],
+    etag: Option<&str>,
+    last_modified: Option<&str>,
+    content_type: Option<&str>,
+) {
+    if fs::create_dir_all(dir).is_err() {
+        return;
+    }
+
+    let (bin_path, meta_path) = entry_paths(dir, url);
+    let _ = fs::write(bin_path, bytes);
+    let meta = format!(
+        "{}\n{}\n{}\n",
+        etag.unwrap_or(""),
+        last_modified.unwrap_or(""),
+        content_type.unwrap_or(""),
+    );
+    let _ = fs::write(meta_path, meta);
+}
+
+/// Map `url` to the `(<bytes file>, <metadata file>)` pair used to store
+/// its cache entry, named after a hash of the URL so that arbitrary URLs
+/// become safe, flat filenames
+fn entry_paths(dir: &Path, url: &Url) -> (PathBuf, PathBuf) {
+    let key = hash_url(url);
+    (dir.join(format!("{}.bin", key)), dir.join(format!("{}.meta", key)))
+}
+
+fn hash_url(url: &Url) -> String {
+    Sha256::digest(url.as_str().as_bytes())
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
@@ -1,4 +1,5 @@
 pub mod asset;
+mod cache;
 pub mod resources;
 pub mod util;
 
@@ -6,13 +7,25 @@ use reqwest::Client;
 use reqwest::Url;
 
 use asset::Asset;
+use asset::DownloadOptions;
 
 pub async fn download_complete_page(url: Url) -> asset::Result<String> {
+    download_complete_page_with_options(url, &DownloadOptions::default()).await
+}
+
+/// Like [download_complete_page], but with full control over [DownloadOptions]
+///
+/// Use this to enable the reader-mode content-extraction pass
+/// (`options.readability`), tune concurrency, or adjust retry behavior.
+pub async fn download_complete_page_with_options(
+    url: Url,
+    options: &DownloadOptions,
+) -> asset::Result<String> {
     let client = Client::new();
     let mut asset = Asset::new(
         url,
-        "text/plain".to_owned(),
+        "text/html".to_owned(),
     );
-    asset.download_complete(&client).await?;
+    asset.download_complete(&client, options).await?;
     asset.try_stringify()
 }
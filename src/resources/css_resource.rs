@@ -0,0 +1,217 @@
+//! A [Resource] for CSS stylesheets
+//!
+//! Discovers `url(...)` references and `@import`ed stylesheets so the
+//! assets they point at can be downloaded and embedded as data URLs.
+
+use bytes::Bytes;
+use lazy_static::lazy_static;
+use regex::Regex;
+use reqwest::Url;
+
+use std::ops::Range;
+use std::str;
+
+use crate::asset::Asset;
+use crate::asset::Error;
+use crate::asset::Resource;
+use crate::asset::Result;
+use crate::util::data_to_dataurl;
+
+lazy_static! {
+    static ref URL_FN: Regex = Regex::new(
+        r#"url\(\s*(?:"(?P<dq>[^"]*)"|'(?P<sq>[^']*)'|(?P<bare>[^"')\s]*))\s*\)"#
+    ).unwrap();
+    static ref IMPORT: Regex = Regex::new(
+        r#"@import\s+(?:"(?P<dq>[^"]*)"|'(?P<sq>[^']*)')"#
+    ).unwrap();
+}
+
+/// How a discovered reference should be spliced back into the stylesheet
+/// once its asset has been rendered
+enum Wrap {
+    /// Replace the whole `url(...)` call with `url(<data url>)`
+    UrlFn,
+
+    /// Replace just the quoted literal (e.g. in `@import "foo.css"`) with a
+    /// quoted data URL, leaving the surrounding `@import`/semicolon alone
+    QuotedString,
+}
+
+/// A parser for CSS stylesheets (and inline/embedded snippets of CSS)
+///
+/// This is used both for linked stylesheets (`<link rel="stylesheet">`) and
+/// for CSS found inline in an HTML document (`<style>` blocks and `style`
+/// attributes), via [HtmlResource][crate::resources::HtmlResource].
+pub struct CssResource {
+    base: Url,
+    data: Option<String>,
+    refs: Vec<(Range<usize>, Wrap, Asset)>,
+}
+
+impl CssResource {
+    /// Produce a new, empty CssResource
+    ///
+    /// `base` is the URL that relative references (`url(...)`, `@import`)
+    /// are resolved against.
+    pub fn new(base: Url) -> CssResource {
+        CssResource {
+            base,
+            data: None,
+            refs: vec![],
+        }
+    }
+
+    /// Whether parsing discovered at least one embeddable reference
+    ///
+    /// Used by [HtmlResource][crate::resources::HtmlResource] to decide
+    /// whether an inline `style` attribute or `<style>` block is worth
+    /// treating as a child asset at all.
+    pub fn has_refs(&self) -> bool {
+        !self.refs.is_empty()
+    }
+
+    /// Resolve the quoted/bare value captured by `found` against `self.base`,
+    /// producing a new child [Asset] for it
+    ///
+    /// Returns `None` for empty values, references that are already data
+    /// URLs, same-document fragment references (e.g. `url(#gradient)`,
+    /// used to point `fill`/`mask`/`clip-path` at an inline SVG element
+    /// rather than an external resource), or values that don't resolve to
+    /// a valid URL.
+    fn resolve_reference(&self, found: &regex::Captures) -> Option<Asset> {
+        let value = found.name("dq")
+            .or_else(|| found.name("sq"))
+            .or_else(|| found.name("bare"))
+            .map(|m| m.as_str().trim())?;
+
+        if value.is_empty() || value.starts_with('#') || value.starts_with("data:") {
+            return None;
+        }
+
+        self.base.join(value).ok().map(|url| Asset::new(url, "".to_owned()))
+    }
+}
+
+impl Resource for CssResource {
+    fn parse(&mut self, bytes: Bytes) -> Result<()> {
+        if self.has_data() {
+            panic!(".parse() called twice on CssResource");
+        }
+
+        let text = str::from_utf8(&bytes)
+            .map_err(|e| Error::ParseError(Box::new(e)))?
+            .to_owned();
+
+        let mut refs = vec![];
+
+        for found in URL_FN.captures_iter(&text) {
+            let whole = found.get(0).unwrap();
+            if let Some(asset) = self.resolve_reference(&found) {
+                refs.push((whole.start()..whole.end(), Wrap::UrlFn, asset));
+            }
+        }
+
+        for found in IMPORT.captures_iter(&text) {
+            // The `dq`/`sq` group only captures the text *between* the
+            // quotes, so the replaced range has to be widened by one byte
+            // on each side to take the quote characters themselves along
+            // with it; otherwise `Wrap::QuotedString`'s re-added quotes end
+            // up doubled.  The `@import` keyword and trailing `;` are left
+            // untouched either way.
+            let literal = found.name("dq").or_else(|| found.name("sq")).unwrap();
+            if let Some(asset) = self.resolve_reference(&found) {
+                refs.push((literal.start() - 1..literal.end() + 1, Wrap::QuotedString, asset));
+            }
+        }
+
+        // Put replacements back in source order so that reversing them at
+        // render time undoes them from the end of the string backwards
+        refs.sort_by_key(|(range, _, _)| range.start);
+
+        self.data = Some(text);
+        self.refs = refs;
+
+        Ok(())
+    }
+
+    fn has_data(&self) -> bool {
+        self.data.is_some()
+    }
+
+    fn needed_assets(&mut self) -> Vec<&mut Asset> {
+        self.refs.iter_mut()
+            .map(|(_, _, asset)| asset)
+            .collect()
+    }
+
+    fn render(&self) -> Result<Bytes> {
+        let mut content = self.data.clone().ok_or(Error::AssetUnloaded)?;
+
+        // Reversed so that earlier ranges remain accurate as later ones are
+        // replaced
+        for (range, wrap, asset) in self.refs.iter().rev() {
+            let rendered = asset.data.as_ref()
+                .ok_or(Error::AssetUnloaded)?
+                .render()?;
+            let dataurl = data_to_dataurl(&asset.mime_hint, &rendered);
+            let replacement = match wrap {
+                Wrap::UrlFn => format!("url({})", dataurl),
+                Wrap::QuotedString => format!("\"{}\"", dataurl),
+            };
+            content.replace_range(range.clone(), &replacement);
+        }
+
+        Ok(content.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use base64::encode;
+    use crate::resources::InertResource;
+
+    /// Parse `input` as CSS, fill in every discovered reference with
+    /// `data` (hinted as `mime`), and render the result back to a string
+    fn render_with(input: &str, data: &[u8], mime: &str) -> String {
+        let mut css = CssResource::new(Url::parse("https://example.com/style.css").unwrap());
+        css.parse(Bytes::copy_from_slice(input.as_bytes())).unwrap();
+
+        for asset in css.needed_assets() {
+            let mut inert = InertResource::default();
+            inert.parse(Bytes::copy_from_slice(data)).unwrap();
+            asset.data = Some(Box::new(inert));
+            asset.mime_hint = mime.to_owned();
+        }
+
+        String::from_utf8(css.render().unwrap().to_vec()).unwrap()
+    }
+
+    #[test]
+    fn url_fn_reference_is_embedded() {
+        let out = render_with(r#"body { background: url("bg.png"); }"#, b"\x89PNG\r\n\x1a\n", "image/png");
+
+        assert!(out.contains("url(data:image/png;base64,"));
+        assert!(!out.contains("bg.png"));
+    }
+
+    #[test]
+    fn import_reference_is_embedded_without_doubling_quotes() {
+        let css = b"a { color: red }";
+        let out = render_with(r#"@import "foo.css";"#, css, "text/css");
+
+        let expected = format!("@import \"data:text/css;base64,{}\";", encode(css));
+        assert_eq!(out, expected);
+        assert!(!out.contains("\"\""));
+    }
+
+    #[test]
+    fn fragment_only_url_is_not_treated_as_a_reference() {
+        let mut css = CssResource::new(Url::parse("https://example.com/style.css").unwrap());
+        css.parse(Bytes::copy_from_slice(br#".grad { fill: url(#gradient); }"#.to_vec().as_slice())).unwrap();
+
+        assert!(!css.has_refs());
+        assert_eq!(css.needed_assets().len(), 0);
+    }
+}
@@ -0,0 +1,416 @@
+//! A [Resource] for HTML documents
+//!
+//! Parses the document with a real HTML parser ([html5ever]) instead of
+//! regex-matching quoted strings, and walks the resulting DOM to find the
+//! handful of places a page can embed a remote asset: `img`/`source`
+//! (`src`/`srcset`), `script[src]`, `link[rel=stylesheet][href]`, inline
+//! `style` attributes, and `<style>` blocks.  Rendering mutates that same
+//! DOM in place and re-serializes it, rather than patching the original
+//! source text.
+
+use bytes::Bytes;
+use html5ever::driver::parse_document;
+use html5ever::serialize::serialize;
+use html5ever::tendril::TendrilSink;
+use html5ever::ParseOpts;
+use markup5ever_rcdom::Handle;
+use markup5ever_rcdom::NodeData;
+use markup5ever_rcdom::RcDom;
+use markup5ever_rcdom::SerializableHandle;
+use reqwest::Url;
+
+use crate::asset::Asset;
+use crate::asset::Error;
+use crate::asset::Integrity;
+use crate::asset::Resource;
+use crate::asset::Result;
+use crate::resources::readability;
+use crate::resources::CssResource;
+use crate::util::data_to_dataurl;
+
+/// Where in the document a discovered reference lives, and how to splice
+/// its rendered asset back in
+enum RefSite {
+    /// A plain attribute (`src`, `href`) whose value should become a data
+    /// URL embedding the referenced asset
+    Attribute { handle: Handle, name: &'static str },
+
+    /// An attribute (`style`, `srcset`) whose value is itself a snippet of
+    /// syntax (CSS, a srcset list) that's been parsed by a sub-`Resource`;
+    /// its *rendered* text (not a data URL) replaces the attribute value
+    SyntaxAttribute { handle: Handle, name: &'static str },
+
+    /// The text content of a `<style>` element
+    StyleText { handle: Handle },
+}
+
+struct PendingRef {
+    site: RefSite,
+    asset: Asset,
+}
+
+/// An HTML [Resource] that discovers embeddable references via a real DOM
+/// parse, instead of regex
+pub struct HtmlResource {
+    url: Url,
+    dom: Option<RcDom>,
+    refs: Vec<PendingRef>,
+    readability: bool,
+}
+
+impl HtmlResource {
+    /// Produce a new, empty HtmlResource for the document at `url`
+    ///
+    /// `url` is used both to resolve relative references and, if the
+    /// document doesn't override it with `<base href>`, as that base
+    /// itself.
+    pub fn new(url: Url) -> HtmlResource {
+        HtmlResource {
+            url,
+            dom: None,
+            refs: vec![],
+            readability: false,
+        }
+    }
+
+    /// Enable (or disable) the reader-mode content-extraction pass
+    ///
+    /// When enabled, [parse][Resource::parse] strips boilerplate (nav,
+    /// ads, sidebars, ...) down to the single highest-scoring content
+    /// element *before* any asset discovery happens, so assets that only
+    /// appeared in stripped-out boilerplate are never even queued for
+    /// download. See [readability] for the scoring heuristic.
+    pub fn with_readability(mut self, readability: bool) -> HtmlResource {
+        self.readability = readability;
+        self
+    }
+
+    /// Find the first `<base href>` in the document, resolved against
+    /// `self.url`, if present
+    fn find_base(&self, handle: &Handle) -> Option<Url> {
+        if let NodeData::Element { ref name, ref attrs, .. } = handle.data {
+            if &*name.local == "base" {
+                for attr in attrs.borrow().iter() {
+                    if &*attr.name.local == "href" {
+                        if let Ok(base) = self.url.join(&attr.value) {
+                            return Some(base);
+                        }
+                    }
+                }
+            }
+        }
+
+        for child in handle.children.borrow().iter() {
+            if let Some(base) = self.find_base(child) {
+                return Some(base);
+            }
+        }
+
+        None
+    }
+
+    /// Recursively walk the DOM from `handle`, appending every embeddable
+    /// reference found to `refs`
+    fn walk(&self, handle: &Handle, base: &Url, refs: &mut Vec<PendingRef>) {
+        if let NodeData::Element { ref name, ref attrs, .. } = handle.data {
+            let tag = &*name.local;
+            let attrs = attrs.borrow();
+            let attr = |wanted: &str| attrs.iter()
+                .find(|a| &*a.name.local == wanted)
+                .map(|a| a.value.to_string());
+
+            let source_attr = match tag {
+                "img" | "source" => Some(("src", "image")),
+                "script" => Some(("src", "application/javascript")),
+                "link" if attr("rel").as_deref() == Some("stylesheet") => Some(("href", "text/css")),
+                _ => None,
+            };
+
+            if let Some((attr_name, mime)) = source_attr {
+                if let Some(value) = attr(attr_name) {
+                    if let Ok(resolved) = base.join(&value) {
+                        let mut asset = Asset::new(resolved, mime.to_owned());
+                        if let Some(integrity) = attr("integrity").and_then(|v| parse_integrity_attr(&v)) {
+                            asset = asset.with_integrity(integrity);
+                        }
+                        refs.push(PendingRef {
+                            site: RefSite::Attribute { handle: handle.clone(), name: attr_name },
+                            asset,
+                        });
+                    }
+                }
+            }
+
+            if matches!(tag, "img" | "source") {
+                if let Some(srcset) = attr("srcset") {
+                    let mut candidates = SrcsetResource::new(base.clone());
+                    if candidates.parse(Bytes::copy_from_slice(srcset.as_bytes())).is_ok()
+                        && candidates.has_refs()
+                    {
+                        refs.push(PendingRef {
+                            site: RefSite::SyntaxAttribute { handle: handle.clone(), name: "srcset" },
+                            asset: Asset::from_resource(base.clone(), "".to_owned(), Box::new(candidates)),
+                        });
+                    }
+                }
+            }
+
+            if let Some(style) = attr("style") {
+                let mut inline = CssResource::new(base.clone());
+                if inline.parse(Bytes::copy_from_slice(style.as_bytes())).is_ok() && inline.has_refs() {
+                    refs.push(PendingRef {
+                        site: RefSite::SyntaxAttribute { handle: handle.clone(), name: "style" },
+                        asset: Asset::from_resource(base.clone(), "text/css".to_owned(), Box::new(inline)),
+                    });
+                }
+            }
+
+            if tag == "style" {
+                let text = collect_text(handle);
+                let mut sheet = CssResource::new(base.clone());
+                if sheet.parse(Bytes::copy_from_slice(text.as_bytes())).is_ok() && sheet.has_refs() {
+                    refs.push(PendingRef {
+                        site: RefSite::StyleText { handle: handle.clone() },
+                        asset: Asset::from_resource(base.clone(), "text/css".to_owned(), Box::new(sheet)),
+                    });
+                }
+            }
+        }
+
+        for child in handle.children.borrow().iter() {
+            self.walk(child, base, refs);
+        }
+    }
+}
+
+impl Resource for HtmlResource {
+    fn parse(&mut self, bytes: Bytes) -> Result<()> {
+        if self.has_data() {
+            panic!(".parse() called twice on HtmlResource");
+        }
+
+        let dom = parse_document(RcDom::default(), ParseOpts::default())
+            .from_utf8()
+            .read_from(&mut bytes.as_ref())
+            .map_err(|e| Error::ParseError(Box::new(e)))?;
+
+        if self.readability {
+            readability::extract_main_content(&dom);
+        }
+
+        let base = self.find_base(&dom.document).unwrap_or_else(|| self.url.clone());
+
+        let mut refs = vec![];
+        self.walk(&dom.document, &base, &mut refs);
+
+        self.dom = Some(dom);
+        self.refs = refs;
+
+        Ok(())
+    }
+
+    fn has_data(&self) -> bool {
+        self.dom.is_some()
+    }
+
+    fn needed_assets(&mut self) -> Vec<&mut Asset> {
+        self.refs.iter_mut()
+            .map(|pending| &mut pending.asset)
+            .collect()
+    }
+
+    fn render(&self) -> Result<Bytes> {
+        let dom = self.dom.as_ref().ok_or(Error::AssetUnloaded)?;
+
+        for pending in self.refs.iter() {
+            let rendered = pending.asset.data.as_ref()
+                .ok_or(Error::AssetUnloaded)?
+                .render()?;
+
+            match &pending.site {
+                RefSite::Attribute { handle, name } => {
+                    let dataurl = data_to_dataurl(&pending.asset.mime_hint, &rendered);
+                    set_attribute(handle, name, &dataurl);
+                },
+                RefSite::SyntaxAttribute { handle, name } => {
+                    let text = String::from_utf8_lossy(&rendered);
+                    set_attribute(handle, name, &text);
+                },
+                RefSite::StyleText { handle } => {
+                    let text = String::from_utf8_lossy(&rendered);
+                    set_text_contents(handle, &text);
+                },
+            }
+        }
+
+        let mut output = Vec::new();
+        serialize(
+            &mut output,
+            &SerializableHandle::from(dom.document.clone()),
+            Default::default(),
+        )
+            .map_err(|e| Error::ParseError(Box::new(e)))?;
+
+        Ok(output.into())
+    }
+}
+
+fn set_attribute(handle: &Handle, name: &str, value: &str) {
+    if let NodeData::Element { ref attrs, .. } = handle.data {
+        let mut attrs = attrs.borrow_mut();
+        if let Some(attr) = attrs.iter_mut().find(|a| &*a.name.local == name) {
+            attr.value = value.into();
+        }
+    }
+}
+
+/// Replace the contents of `handle`'s first text-node child with `text`
+///
+/// Used for `<style>` blocks, which are expected to contain exactly one
+/// text node.
+fn set_text_contents(handle: &Handle, text: &str) {
+    for child in handle.children.borrow().iter() {
+        if let NodeData::Text { ref contents } = child.data {
+            let mut contents = contents.borrow_mut();
+            contents.clear();
+            contents.push_slice(text);
+            return;
+        }
+    }
+}
+
+/// Parse an `integrity` attribute value (one or more whitespace-separated
+/// SRI digests) and return the strongest one understood, if any
+///
+/// Per the SRI spec, when multiple digests are given, verification only
+/// needs to use the strongest one, so weaker alternate digests (kept
+/// around for older clients) are simply ignored here.
+fn parse_integrity_attr(value: &str) -> Option<Integrity> {
+    value.split_whitespace()
+        .filter_map(Integrity::parse)
+        .max_by_key(|integrity| integrity.algorithm as u8)
+}
+
+/// Concatenate the text content of all of `handle`'s direct text-node
+/// children
+fn collect_text(handle: &Handle) -> String {
+    handle.children.borrow().iter()
+        .filter_map(|child| match &child.data {
+            NodeData::Text { contents } => Some(contents.borrow().to_string()),
+            _ => None,
+        })
+        .collect()
+}
+
+/// A small helper [Resource] that parses the `srcset` attribute syntax
+/// (a comma-separated list of `<url> <descriptor>?` candidates) so that
+/// each candidate image can be downloaded and embedded like any other
+/// asset, while preserving the descriptors on render
+struct SrcsetResource {
+    base: Url,
+    candidates: Option<Vec<(String, Asset)>>,
+}
+
+impl SrcsetResource {
+    fn new(base: Url) -> SrcsetResource {
+        SrcsetResource { base, candidates: None }
+    }
+
+    fn has_refs(&self) -> bool {
+        self.candidates.as_ref().map_or(false, |c| !c.is_empty())
+    }
+}
+
+impl Resource for SrcsetResource {
+    fn parse(&mut self, bytes: Bytes) -> Result<()> {
+        if self.has_data() {
+            panic!(".parse() called twice on SrcsetResource");
+        }
+
+        let text = std::str::from_utf8(&bytes)
+            .map_err(|e| Error::ParseError(Box::new(e)))?;
+
+        let candidates = text.split(',')
+            .filter_map(|candidate| {
+                let candidate = candidate.trim();
+                let (url, descriptor) = match candidate.find(char::is_whitespace) {
+                    Some(split) => (&candidate[..split], candidate[split..].trim()),
+                    None => (candidate, ""),
+                };
+                if url.is_empty() {
+                    return None;
+                }
+                self.base.join(url).ok()
+                    .map(|resolved| (descriptor.to_owned(), Asset::new(resolved, "image".to_owned())))
+            })
+            .collect();
+
+        self.candidates = Some(candidates);
+
+        Ok(())
+    }
+
+    fn has_data(&self) -> bool {
+        self.candidates.is_some()
+    }
+
+    fn needed_assets(&mut self) -> Vec<&mut Asset> {
+        self.candidates.as_mut()
+            .map(|c| c.iter_mut().map(|(_, asset)| asset).collect())
+            .unwrap_or_default()
+    }
+
+    fn render(&self) -> Result<Bytes> {
+        let candidates = self.candidates.as_ref().ok_or(Error::AssetUnloaded)?;
+
+        let rendered = candidates.iter()
+            .map(|(descriptor, asset)| {
+                let bytes = asset.data.as_ref()
+                    .ok_or(Error::AssetUnloaded)?
+                    .render()?;
+                let dataurl = data_to_dataurl(&asset.mime_hint, &bytes);
+                Ok(if descriptor.is_empty() {
+                    dataurl
+                } else {
+                    format!("{} {}", dataurl, descriptor)
+                })
+            })
+            .collect::<Result<Vec<String>>>()?
+            .join(", ");
+
+        Ok(rendered.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::resources::InertResource;
+
+    #[test]
+    fn img_src_is_rewritten_to_a_data_url() {
+        let base = Url::parse("https://example.com/page.html").unwrap();
+        let mut html = HtmlResource::new(base.clone());
+        html.parse(Bytes::copy_from_slice(
+            b"<html><body><img src=\"logo.png\"></body></html>",
+        )).unwrap();
+
+        let needed = html.needed_assets();
+        assert_eq!(needed.len(), 1);
+
+        let asset = needed.into_iter().next().unwrap();
+        assert_eq!(asset.url, base.join("logo.png").unwrap());
+
+        let mut inert = InertResource::default();
+        inert.parse(Bytes::copy_from_slice(b"\x89PNG\r\n\x1a\n")).unwrap();
+        asset.data = Some(Box::new(inert));
+        asset.mime_hint = "image/png".to_owned();
+
+        let rendered = html.render().unwrap();
+        let rendered = String::from_utf8(rendered.to_vec()).unwrap();
+
+        assert!(rendered.contains("src=\"data:image/png;base64,"));
+        assert!(!rendered.contains("logo.png"));
+    }
+}
@@ -32,6 +32,6 @@ impl Resource for InertResource {
     }
 
     fn render(&self) -> Result<Bytes> {
-        self.data.clone().ok_or(Error::ResourceUnloaded)
+        self.data.clone().ok_or(Error::AssetUnloaded)
     }
 }
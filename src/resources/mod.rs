@@ -0,0 +1,12 @@
+//! [Resource] implementations for specific content types
+//!
+//! [Resource]: crate::asset::Resource
+
+mod css_resource;
+mod html_resource;
+mod inert_resource;
+mod readability;
+
+pub use css_resource::CssResource;
+pub use html_resource::HtmlResource;
+pub use inert_resource::InertResource;
@@ -0,0 +1,270 @@
+//! A "reader mode" content-extraction pass for [HtmlResource][super::HtmlResource]
+//!
+//! Implements the classic text-density scoring heuristic: short
+//! paragraph-like elements (`<p>`, `<li>`, `<td>`, ...) each contribute a
+//! base score built from the amount of text they contain that *isn't*
+//! inside a link, with a small bonus per comma (prose tends to have more
+//! commas than, say, a navigation menu). That score is propagated up to
+//! the element's parent, grandparent, and great-grandparent with
+//! decreasing weight, so the actual content container accumulates credit
+//! from all the paragraphs nested inside it without that credit climbing
+//! all the way up to `<body>`. Candidates are further penalized by link
+//! density (the fraction of their text that's inside an `<a>`), so a
+//! boilerplate wrapper that happens to sit a few levels above real prose
+//! doesn't win just by proximity. Known boilerplate tags (`nav`, `aside`,
+//! `footer`, ...) and anything whose `class`/`id` looks like a sidebar,
+//! ad, or comment section are excluded outright. The single
+//! highest-scoring element is kept as the document's main content;
+//! everything else under `<body>` is discarded.
+
+use markup5ever_rcdom::Handle;
+use markup5ever_rcdom::NodeData;
+use markup5ever_rcdom::RcDom;
+
+use std::collections::HashMap;
+use std::rc::Rc;
+
+/// Tags that are never scored as content: nothing inside them seeds a
+/// score.  They are removed only along with the rest of `<body>` outside the
+/// winning element; any nested inside the winning element are kept.
+const EXCLUDED_TAGS: &[&str] = &[
+    "nav", "aside", "footer", "header", "script", "style", "noscript", "form", "iframe",
+];
+
+/// Tags whose own text seeds a content score, which is then propagated up
+/// to nearby ancestors -- real prose is usually structured as a handful of
+/// these nested a few levels inside one true content container, rather
+/// than one container holding a lone giant text blob
+const SEED_TAGS: &[&str] = &["p", "pre", "td", "li", "blockquote"];
+
+/// Substrings in a `class`/`id` that mark a node as boilerplate
+const NEGATIVE_HINTS: &[&str] = &[
+    "sidebar", "nav", "advert", "ad-", "comment", "footer", "header",
+    "banner", "popup", "social", "share", "widget",
+];
+
+/// Decay applied as a seed score is added to each successive ancestor:
+/// the parent gets it in full, the grandparent half, the great-grandparent
+/// a quarter, and it stops there -- far enough to credit the actual
+/// content container without letting that credit climb all the way up to
+/// `<body>` and make the whole document look like the winning subtree
+const ANCESTOR_DECAY: [f64; 3] = [1.0, 0.5, 0.25];
+
+/// Prune `dom` in place, replacing the contents of `<body>` with just the
+/// single highest-scoring content element found
+///
+/// If `<body>` is missing, or nothing scores above zero (e.g. the page has
+/// no real prose content), the document is left untouched.
+pub fn extract_main_content(dom: &RcDom) {
+    let body = match find_tag(&dom.document, "body") {
+        Some(body) => body,
+        None => return,
+    };
+
+    let mut scores = HashMap::new();
+    seed_scores(&body, &mut scores);
+
+    let mut best: Option<(Handle, f64)> = None;
+    find_best(&body, &scores, &mut best);
+
+    if let Some((content, score)) = best {
+        if score > 0.0 && !Rc::ptr_eq(&content, &body) {
+            content.parent.set(Some(Rc::downgrade(&body)));
+            *body.children.borrow_mut() = vec![content];
+        }
+    }
+}
+
+fn find_tag(handle: &Handle, tag: &str) -> Option<Handle> {
+    if let NodeData::Element { ref name, .. } = handle.data {
+        if &*name.local == tag {
+            return Some(handle.clone());
+        }
+    }
+
+    handle.children.borrow().iter()
+        .find_map(|child| find_tag(child, tag))
+}
+
+fn looks_like_boilerplate(handle: &Handle) -> bool {
+    let attrs = match handle.data {
+        NodeData::Element { ref attrs, .. } => attrs,
+        _ => return false,
+    };
+
+    attrs.borrow().iter().any(|attr| {
+        matches!(&*attr.name.local, "class" | "id")
+            && NEGATIVE_HINTS.iter().any(|hint| attr.value.to_lowercase().contains(hint))
+    })
+}
+
+/// Walk `handle`, seeding a content score at every [SEED_TAGS] element and
+/// propagating it up through [ANCESTOR_DECAY], recording every node's
+/// accumulated score in `scores`
+///
+/// Stops descending into [EXCLUDED_TAGS] or boilerplate-looking elements,
+/// so nothing nested inside them is ever seeded.
+fn seed_scores(handle: &Handle, scores: &mut HashMap<usize, f64>) {
+    if let NodeData::Element { ref name, .. } = handle.data {
+        let tag = &*name.local;
+
+        if EXCLUDED_TAGS.contains(&tag) || looks_like_boilerplate(handle) {
+            return;
+        }
+
+        if SEED_TAGS.contains(&tag) {
+            propagate(handle, seed_score(handle), scores);
+        }
+    }
+
+    for child in handle.children.borrow().iter() {
+        seed_scores(child, scores);
+    }
+}
+
+/// Base content score for a [SEED_TAGS] element: its own text density
+/// (text minus link text) plus a comma bonus and a flat per-paragraph
+/// bonus, so that having more candidate paragraphs is itself a signal
+fn seed_score(handle: &Handle) -> f64 {
+    let text = collect_text(handle);
+    let link_text = collect_link_text(handle);
+    let density = text.chars().count().saturating_sub(link_text.chars().count()) as f64;
+    let commas = text.matches(',').count() as f64;
+
+    density / 100.0 + commas + 1.0
+}
+
+/// Add `base` to `handle`'s score, then to its parent/grandparent/
+/// great-grandparent scaled by [ANCESTOR_DECAY]
+fn propagate(handle: &Handle, base: f64, scores: &mut HashMap<usize, f64>) {
+    *scores.entry(node_key(handle)).or_insert(0.0) += base;
+
+    let mut ancestor = parent_of(handle);
+    for decay in ANCESTOR_DECAY.iter() {
+        let node = match ancestor {
+            Some(node) => node,
+            None => break,
+        };
+        *scores.entry(node_key(&node)).or_insert(0.0) += base * decay;
+        ancestor = parent_of(&node);
+    }
+}
+
+/// Read `handle`'s parent out of its `Cell` without disturbing it
+fn parent_of(handle: &Handle) -> Option<Handle> {
+    let weak = handle.parent.take();
+    handle.parent.set(weak.clone());
+    weak.and_then(|weak| weak.upgrade())
+}
+
+fn node_key(handle: &Handle) -> usize {
+    Rc::as_ptr(handle) as usize
+}
+
+/// Find the element in `scores` with the highest link-density-penalized
+/// score, keeping `best` updated as the tree is walked
+fn find_best(handle: &Handle, scores: &HashMap<usize, f64>, best: &mut Option<(Handle, f64)>) {
+    if matches!(handle.data, NodeData::Element { .. }) {
+        if let Some(&raw) = scores.get(&node_key(handle)) {
+            let score = raw * (1.0 - link_density(handle));
+            if best.as_ref().map_or(true, |(_, best_score)| score > *best_score) {
+                *best = Some((handle.clone(), score));
+            }
+        }
+    }
+
+    for child in handle.children.borrow().iter() {
+        find_best(child, scores, best);
+    }
+}
+
+/// The fraction of `handle`'s text that sits inside an `<a>` element
+fn link_density(handle: &Handle) -> f64 {
+    let text = collect_text(handle);
+    if text.is_empty() {
+        return 0.0;
+    }
+
+    collect_link_text(handle).chars().count() as f64 / text.chars().count() as f64
+}
+
+/// Concatenate the text of every descendant text node
+fn collect_text(handle: &Handle) -> String {
+    let mut text = String::new();
+    append_text(handle, &mut text);
+    text
+}
+
+fn append_text(handle: &Handle, out: &mut String) {
+    if let NodeData::Text { ref contents } = handle.data {
+        out.push_str(&contents.borrow());
+    }
+    for child in handle.children.borrow().iter() {
+        append_text(child, out);
+    }
+}
+
+/// Concatenate the text of every descendant text node that's inside an
+/// `<a>` element
+fn collect_link_text(handle: &Handle) -> String {
+    let mut text = String::new();
+    append_link_text(handle, &mut text);
+    text
+}
+
+fn append_link_text(handle: &Handle, out: &mut String) {
+    let is_anchor = matches!(&handle.data, NodeData::Element { name, .. } if &*name.local == "a");
+    if is_anchor {
+        append_text(handle, out);
+        return;
+    }
+    for child in handle.children.borrow().iter() {
+        append_link_text(child, out);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use html5ever::driver::parse_document;
+    use html5ever::serialize::serialize;
+    use html5ever::tendril::TendrilSink;
+    use html5ever::ParseOpts;
+    use markup5ever_rcdom::SerializableHandle;
+
+    fn extract(html: &str) -> String {
+        let dom = parse_document(RcDom::default(), ParseOpts::default())
+            .from_utf8()
+            .read_from(&mut html.as_bytes())
+            .unwrap();
+
+        extract_main_content(&dom);
+
+        let mut output = Vec::new();
+        serialize(
+            &mut output,
+            &SerializableHandle::from(dom.document.clone()),
+            Default::default(),
+        ).unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn prose_nested_in_a_wrapper_div_outscores_the_wrapper() {
+        let rendered = extract(
+            r#"<html><body>
+                <div id="wrapper">
+                    <div class="menu"><a href="/a">A</a><a href="/b">B</a><a href="/c">C</a></div>
+                    <article>
+                        <p>The quick brown fox jumps over the lazy dog, again and again, tirelessly.</p>
+                        <p>Prose needs more than one paragraph to really prove the point, so here's another, with a few commas, just in case.</p>
+                    </article>
+                </div>
+            </body></html>"#,
+        );
+
+        assert!(rendered.contains("tirelessly"));
+        assert!(!rendered.contains("class=\"menu\""));
+    }
+}
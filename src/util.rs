@@ -1,4 +1,5 @@
 use base64::encode;
+use reqwest::Url;
 
 const MAGIC: [(&[u8], &str); 19] = [
     // Image
@@ -25,6 +26,43 @@ const MAGIC: [(&[u8], &str); 19] = [
     (b"\x1A\x45\xDF\xA3", "video/webm"),
 ];
 
+const EXTENSIONS: [(&str, &str); 20] = [
+    (".html", "text/html"),
+    (".htm", "text/html"),
+    (".css", "text/css"),
+    (".js", "application/javascript"),
+    (".json", "application/json"),
+    (".png", "image/png"),
+    (".jpg", "image/jpeg"),
+    (".jpeg", "image/jpeg"),
+    (".gif", "image/gif"),
+    (".svg", "image/svg+xml"),
+    (".webp", "image/webp"),
+    (".ico", "image/x-icon"),
+    (".mp3", "audio/mpeg"),
+    (".ogg", "audio/ogg"),
+    (".wav", "audio/wav"),
+    (".flac", "audio/x-flac"),
+    (".mp4", "video/mp4"),
+    (".webm", "video/webm"),
+    (".avi", "video/avi"),
+    (".mov", "video/quicktime"),
+];
+
+/// Guess a MIME type from `url`'s path extension, for use once a response's
+/// `Content-Type` has turned out to be missing or unhelpful
+///
+/// Returns an empty string, like [detect_mimetype], if nothing matches.
+pub fn guess_mime_from_extension(url: &Url) -> &'static str {
+    let path = url.path().to_lowercase();
+    for (ext, mime) in EXTENSIONS.iter() {
+        if path.ends_with(ext) {
+            return mime;
+        }
+    }
+    ""
+}
+
 pub fn detect_mimetype(data: &[u8]) -> &str {
     for (magic_bytes, mime) in MAGIC.iter() {
         if data.starts_with(magic_bytes) {
@@ -42,3 +80,16 @@ pub fn data_to_dataurl(mime: &str, data: &[u8]) -> String {
     };
     format!("data:{};base64,{}", mimetype, encode(data))
 }
+
+/// Normalize a [Url] for use as a download cache key
+///
+/// Two URLs that only differ by fragment (`#...`) point at the exact same
+/// downloaded bytes, so the fragment is stripped before the URL is used to
+/// key the in-flight download cache in [Asset::download_complete][1].
+///
+/// [1]: crate::asset::Asset::download_complete
+pub fn normalize_url(url: &Url) -> Url {
+    let mut normalized = url.clone();
+    normalized.set_fragment(None);
+    normalized
+}